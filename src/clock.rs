@@ -0,0 +1,59 @@
+//! An injectable clock for deterministic testing and automatic stamping.
+
+use chrono::prelude::*;
+use std::{sync::Arc, sync::Mutex, time::Duration};
+
+/// A source of the current time.
+pub trait Clock {
+    /// The current instant in UTC.
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// A clock backed by the operating system wall clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+impl<T: Clock + ?Sized> Clock for Arc<T> {
+    fn now(&self) -> DateTime<Utc> {
+        (**self).now()
+    }
+}
+
+/// A clock whose time is set and advanced manually, for reproducible tests.
+pub struct SimulatedClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl SimulatedClock {
+    /// Create a simulated clock fixed at `start`.
+    pub fn new(start: DateTime<Utc>) -> Self {
+        SimulatedClock {
+            now: Mutex::new(start),
+        }
+    }
+
+    /// Advance the clock by `delta`.
+    pub fn advance(&self, delta: Duration) {
+        if let Ok(delta) = chrono::Duration::from_std(delta) {
+            let mut now = self.now.lock().unwrap();
+            *now = *now + delta;
+        }
+    }
+
+    /// Set the clock to an absolute instant.
+    pub fn set(&self, time: DateTime<Utc>) {
+        *self.now.lock().unwrap() = time;
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
@@ -0,0 +1,214 @@
+//! Pluggable column formatting for the CSV recorder.
+
+use chrono::prelude::*;
+use msr::Value;
+use std::{collections::HashMap, time::Duration};
+
+/// How a value is coerced into its textual column representation.
+#[derive(Debug, Clone)]
+pub enum Conversion {
+    /// Keep the value's natural string form.
+    AsIs,
+    /// Render as an integer.
+    Integer,
+    /// Render as a floating point number.
+    Float,
+    /// Render as `true`/`false`.
+    Boolean,
+    /// Interpret a numeric value as epoch milliseconds and format it.
+    Timestamp(String),
+}
+
+/// How `Value::Bin` payloads are encoded into a column.
+#[derive(Debug, Clone, Copy)]
+pub enum BinaryEncoding {
+    /// Drop the payload, emitting an empty cell (the historic behaviour).
+    Drop,
+    /// Standard base64, with padding.
+    Base64,
+    /// Lower-case hexadecimal.
+    Hex,
+}
+
+/// How `Value::Timeout` is rendered.
+#[derive(Debug, Clone, Copy)]
+pub enum TimeoutFormat {
+    /// `true` once the timeout has elapsed (the historic behaviour).
+    ElapsedBoolean,
+    /// Remaining duration in milliseconds.
+    RemainingMillis,
+}
+
+/// Column-formatting configuration.
+pub struct ConversionConfig {
+    /// Conversion applied to keys without a specific override.
+    pub default: Conversion,
+    /// Per-key conversion overrides.
+    pub per_key: HashMap<String, Conversion>,
+    /// How binary payloads are captured.
+    pub binary: BinaryEncoding,
+    /// How timeouts are rendered.
+    pub timeout: TimeoutFormat,
+}
+
+impl Default for ConversionConfig {
+    /// The defaults reproduce the recorder's historic column behaviour.
+    fn default() -> Self {
+        ConversionConfig {
+            default: Conversion::AsIs,
+            per_key: HashMap::new(),
+            binary: BinaryEncoding::Drop,
+            timeout: TimeoutFormat::ElapsedBoolean,
+        }
+    }
+}
+
+impl ConversionConfig {
+    /// Format a value for the given key, returning the cell contents.
+    ///
+    /// Returns `None` when the payload is intentionally discarded (e.g. a
+    /// binary value under [`BinaryEncoding::Drop`]) so the caller can emit
+    /// an empty cell with a warning, matching the original code path.
+    pub fn format(&self, key: &str, value: &Value) -> Option<String> {
+        match value {
+            Value::Bin(data) => match self.binary {
+                BinaryEncoding::Drop => None,
+                BinaryEncoding::Base64 => Some(base64_encode(data)),
+                BinaryEncoding::Hex => Some(hex_encode(data)),
+            },
+            Value::Timeout(t) => Some(match self.timeout {
+                TimeoutFormat::ElapsedBoolean => (*t == Duration::new(0, 0)).to_string(),
+                TimeoutFormat::RemainingMillis => (t.as_millis() as u64).to_string(),
+            }),
+            _ => {
+                let conversion = self.per_key.get(key).unwrap_or(&self.default);
+                Some(apply(conversion, value))
+            }
+        }
+    }
+}
+
+/// Apply a conversion to a non-binary, non-timeout value.
+fn apply(conversion: &Conversion, value: &Value) -> String {
+    match conversion {
+        Conversion::AsIs => as_is(value),
+        Conversion::Integer => numeric(value).map(|n| (n as i64).to_string()),
+        Conversion::Float => numeric(value).map(|n| n.to_string()),
+        Conversion::Boolean => boolean(value).map(|b| b.to_string()),
+        Conversion::Timestamp(fmt) => numeric(value)
+            .map(|n| Utc.timestamp_millis(n as i64).format(fmt).to_string()),
+    }
+    .unwrap_or_else(|| as_is(value))
+}
+
+/// The value's natural string form.
+fn as_is(value: &Value) -> String {
+    match value {
+        Value::Decimal(d) => d.to_string(),
+        Value::Integer(i) => i.to_string(),
+        Value::Bit(b) => b.to_string(),
+        Value::Text(t) => t.clone(),
+        Value::Timeout(t) => (*t == Duration::new(0, 0)).to_string(),
+        Value::Bin(_) => String::new(),
+    }
+}
+
+/// Coerce a value into a number where it is meaningful to do so.
+fn numeric(value: &Value) -> Option<f64> {
+    match value {
+        Value::Decimal(d) => Some(*d),
+        Value::Integer(i) => Some(*i as f64),
+        Value::Bit(b) => Some(if *b { 1.0 } else { 0.0 }),
+        Value::Text(t) => t.parse::<f64>().ok(),
+        _ => None,
+    }
+}
+
+/// Coerce a value into a boolean where it is meaningful to do so.
+fn boolean(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bit(b) => Some(*b),
+        Value::Integer(i) => Some(*i != 0),
+        Value::Decimal(d) => Some(*d != 0.0),
+        Value::Text(t) => t.parse::<bool>().ok(),
+        _ => None,
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard base64 encoding with padding.
+fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        out.push(BASE64[(n >> 18 & 0x3f) as usize] as char);
+        out.push(BASE64[(n >> 12 & 0x3f) as usize] as char);
+        out.push(if chunk.len() > 1 {
+            BASE64[(n >> 6 & 0x3f) as usize] as char
+        } else {
+            '='
+        });
+        out.push(if chunk.len() > 2 {
+            BASE64[(n & 0x3f) as usize] as char
+        } else {
+            '='
+        });
+    }
+    out
+}
+
+/// Lower-case hexadecimal encoding.
+fn hex_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len() * 2);
+    for byte in data {
+        out.push_str(&format!("{:02x}", byte));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_matches_known_vectors() {
+        // The classic RFC 4648 test vectors exercise every tail length.
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(b"foob"), "Zm9vYg==");
+        assert_eq!(base64_encode(b"fooba"), "Zm9vYmE=");
+        assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn hex_matches_known_vectors() {
+        assert_eq!(hex_encode(b""), "");
+        assert_eq!(hex_encode(&[0x00, 0x0f, 0xff]), "000fff");
+        assert_eq!(hex_encode(b"foo"), "666f6f");
+    }
+
+    #[test]
+    fn binary_encoding_is_applied_per_config() {
+        let mut cfg = ConversionConfig::default();
+        cfg.binary = BinaryEncoding::Base64;
+        assert_eq!(
+            cfg.format("b", &Value::Bin(b"foo".to_vec())),
+            Some("Zm9v".to_string())
+        );
+        cfg.binary = BinaryEncoding::Hex;
+        assert_eq!(
+            cfg.format("b", &Value::Bin(b"foo".to_vec())),
+            Some("666f6f".to_string())
+        );
+        cfg.binary = BinaryEncoding::Drop;
+        assert_eq!(cfg.format("b", &Value::Bin(b"foo".to_vec())), None);
+    }
+}
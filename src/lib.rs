@@ -2,12 +2,41 @@ use chrono::prelude::*;
 use csv::Writer;
 use log::warn;
 use msr::*;
-use std::{collections::HashMap, fs::OpenOptions, io::Result, path::PathBuf, time::Duration};
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    io::{Result, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+mod clock;
+mod conversion;
+mod prometheus;
+mod reader;
+mod retry;
+mod rolling;
+
+pub use crate::clock::{Clock, SimulatedClock, SystemClock};
+pub use crate::conversion::{BinaryEncoding, Conversion, ConversionConfig, TimeoutFormat};
+pub use crate::prometheus::PrometheusExporter;
+pub use crate::reader::{CsvReader, CsvReaderConfig, ValueKind};
+pub use crate::retry::{BufferStatus, RetryConfig};
+pub use crate::rolling::{
+    RetentionPolicy, RollingFileConfig, RollingFileLimits, RollingFileNameTemplate,
+    RollingFileWriter,
+};
 
 /// A simple CSV recoder implementation.
 pub struct CsvRecorder {
     created_header: bool,
     states: Vec<(DateTime<Utc>, HashMap<String, Value>)>,
+    rolling: Option<RollingFileWriter>,
+    conversions: ConversionConfig,
+    clock: Box<dyn Clock>,
+    overflow_header: bool,
+    pending: usize,
+    dropped: usize,
     cfg: CsvRecorderConfig,
 }
 
@@ -19,6 +48,19 @@ pub struct CsvRecorderConfig {
     pub key_list: Vec<String>,
     /// Time formatting option.
     pub time_format: Option<String>,
+    /// Optional rolling-file setup.
+    ///
+    /// When set, [`persist`](CsvRecorder::persist) writes into a sequence of
+    /// size/time-bounded segments instead of appending to `file_name`
+    /// indefinitely.
+    pub rolling: Option<RollingFileConfig>,
+    /// Optional retry/overflow behaviour for failed flushes.
+    pub retry: Option<RetryConfig>,
+    /// Optional per-column formatting overrides.
+    ///
+    /// When `None` the recorder keeps its historic behaviour: natural string
+    /// forms, dropped binary payloads, elapsed-boolean timeouts.
+    pub conversions: Option<ConversionConfig>,
 }
 
 /// Get a list of values names that can be recorded.
@@ -159,10 +201,23 @@ impl RecVals for SystemState {
 }
 
 impl CsvRecorder {
-    /// Create a new recorder instance.
+    /// Create a new recorder instance stamping records with the system clock.
     pub fn new(cfg: CsvRecorderConfig) -> Self {
+        CsvRecorder::with_clock(cfg, Box::new(SystemClock))
+    }
+
+    /// Create a new recorder instance with an explicit clock.
+    pub fn with_clock(mut cfg: CsvRecorderConfig, clock: Box<dyn Clock>) -> Self {
+        let rolling = cfg.rolling.take().map(RollingFileWriter::new);
+        let conversions = cfg.conversions.take().unwrap_or_default();
         CsvRecorder {
             created_header: false,
+            rolling,
+            conversions,
+            clock,
+            overflow_header: false,
+            pending: 0,
+            dropped: 0,
             cfg,
             states: vec![],
         }
@@ -173,13 +228,138 @@ impl CsvRecorder {
         self.states.push((time, values));
     }
 
+    /// Add a map of values stamped with the injected clock.
+    pub fn record_now(&mut self, values: HashMap<String, Value>) {
+        let time = self.clock.now();
+        self.record(time, values);
+    }
+
+    /// Current backpressure counters.
+    pub fn status(&self) -> BufferStatus {
+        BufferStatus {
+            pending: self.pending,
+            dropped: self.dropped,
+        }
+    }
+
+    /// Build the header row.
+    fn header_record(&self) -> Vec<String> {
+        let mut rec = vec!["timestamp_utc".to_string()];
+        rec.extend_from_slice(self.cfg.key_list.as_slice());
+        rec
+    }
+
+    /// Build a single data row for the given timestamped state.
+    fn value_record(&self, time: &DateTime<Utc>, state: &HashMap<String, Value>) -> Vec<String> {
+        let vals: Vec<_> = self
+            .cfg
+            .key_list
+            .iter()
+            .map(|key| match state.get(key) {
+                Some(v) => self.conversions.format(key, v).unwrap_or_else(|| {
+                    warn!("The binary data of '{}' will not be recorded", key);
+                    "".to_string()
+                }),
+                None => "".to_string(),
+            })
+            .collect();
+
+        let mut rec = vec![];
+        if let Some(ref fmt) = self.cfg.time_format {
+            rec.push(time.format(&fmt).to_string());
+        } else {
+            rec.push(time.timestamp_millis().to_string());
+        }
+        rec.extend_from_slice(vals.as_slice());
+        rec
+    }
+
     /// Write buffred values to disk.
+    ///
+    /// The flush is retried according to the configured [`RetryConfig`].
+    /// Because progress is tracked per record, a retry resumes from the
+    /// first unwritten record instead of re-sorting and re-emitting the
+    /// whole buffer, so a mid-write failure can never duplicate rows. If
+    /// every attempt fails the unwritten tail is either dumped to the
+    /// configured overflow path (counted as `dropped`) or kept buffered
+    /// (counted as `pending`) for the next call.
     pub fn persist(&mut self) -> Result<()> {
         if self.states.is_empty() {
             warn!("no states to persist");
             return Ok(());
         }
 
+        self.states.sort_by(|(t1, _), (t2, _)| t1.cmp(&t2));
+
+        let (max_attempts, backoff, overflow) = match self.cfg.retry {
+            Some(ref r) => (r.max_attempts.max(1), r.backoff, r.overflow.clone()),
+            None => (1, Duration::new(0, 0), None),
+        };
+
+        let states = std::mem::take(&mut self.states);
+        let mut offset = 0;
+        let mut last_err = None;
+
+        for attempt in 0..max_attempts {
+            if attempt > 0 {
+                std::thread::sleep(backoff);
+            }
+            match self.flush_from(&states, &mut offset) {
+                Ok(()) => {
+                    self.pending = 0;
+                    return Ok(());
+                }
+                Err(e) => {
+                    warn!("persist attempt {} failed: {}", attempt + 1, e);
+                    last_err = Some(e);
+                }
+            }
+        }
+
+        // All attempts exhausted: deal with the unwritten tail.
+        let remaining = states[offset..].to_vec();
+        match overflow {
+            Some(path) => match self.dump_overflow(&path, &remaining) {
+                Ok(()) => {
+                    self.dropped += remaining.len();
+                    self.pending = 0;
+                }
+                Err(e) => {
+                    warn!("unable to dump to overflow '{}': {}", path.display(), e);
+                    self.pending = remaining.len();
+                    self.states = remaining;
+                }
+            },
+            None => {
+                self.pending = remaining.len();
+                self.states = remaining;
+            }
+        }
+        Err(last_err.unwrap_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::Other, "persist failed")
+        }))
+    }
+
+    /// Flush `states[*offset..]`, advancing `offset` past every record that
+    /// reaches disk so a later retry resumes from the right place.
+    fn flush_from(
+        &mut self,
+        states: &[(DateTime<Utc>, HashMap<String, Value>)],
+        offset: &mut usize,
+    ) -> Result<()> {
+        if self.rolling.is_some() {
+            self.flush_rolling(states, offset)
+        } else {
+            self.flush_single(states, offset)
+        }
+    }
+
+    /// Append the buffer tail to the single configured file.
+    fn flush_single(
+        &mut self,
+        states: &[(DateTime<Utc>, HashMap<String, Value>)],
+        offset: &mut usize,
+    ) -> Result<()> {
         let file = OpenOptions::new()
             .create(true)
             .write(true)
@@ -188,51 +368,204 @@ impl CsvRecorder {
 
         let mut writer = Writer::from_writer(file);
 
-        self.states.sort_by(|(t1, _), (t2, _)| t1.cmp(&t2));
-
-        for (time, state) in self.states.iter() {
-            if !self.created_header {
-                let mut rec = vec!["timestamp_utc".to_string()];
-                rec.extend_from_slice(self.cfg.key_list.as_slice());
-                writer.write_record(rec)?;
+        while *offset < states.len() {
+            let (time, state) = &states[*offset];
+            let need_header = !self.created_header;
+            if need_header {
+                writer.write_record(self.header_record())?;
+            }
+            writer.write_record(self.value_record(time, state))?;
+            writer.flush()?;
+            // Only commit the header as written once the flush made it durable.
+            if need_header {
                 self.created_header = true;
             }
+            *offset += 1;
+        }
+        Ok(())
+    }
 
-            let vals: Vec<_> = self
-                .cfg
-                .key_list
-                .iter()
-                .map(|key| match state.get(key) {
-                    Some(v) => {
-                        use crate::Value::*;
-                        match v {
-                            Decimal(d) => d.to_string(),
-                            Integer(i) => i.to_string(),
-                            Bit(b) => b.to_string(),
-                            Text(t) => t.clone(),
-                            Timeout(t) => (*t == Duration::new(0, 0)).to_string(),
-                            Bin(_) => {
-                                warn!("The binary data of '{}' will not be recorded", key);
-                                "".to_string()
-                            }
-                        }
-                    }
-                    None => "".to_string(),
-                })
-                .collect();
+    /// Distribute the buffer tail across rolling segments, re-emitting the
+    /// header for every freshly opened segment and pruning old ones after.
+    fn flush_rolling(
+        &mut self,
+        states: &[(DateTime<Utc>, HashMap<String, Value>)],
+        offset: &mut usize,
+    ) -> Result<()> {
+        while *offset < states.len() {
+            let (time, state) = &states[*offset];
+            let mut bytes = encode_record(&self.value_record(time, state))?;
 
-            let mut rec = vec![];
+            let rolling = self.rolling.as_mut().expect("rolling writer");
+            let (path, needs_header) = rolling.segment_for(*time, bytes.len() as u64);
 
-            if let Some(ref fmt) = self.cfg.time_format {
-                rec.push(time.format(&fmt).to_string());
-            } else {
-                rec.push(time.timestamp_millis().to_string());
+            if needs_header {
+                let mut line = encode_record(&self.header_record())?;
+                line.append(&mut bytes);
+                bytes = line;
             }
-            rec.extend_from_slice(vals.as_slice());
-            writer.write_record(rec)?;
+
+            let mut file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(true)
+                .open(&path)?;
+            file.write_all(&bytes)?;
+            file.flush()?;
+
+            let rolling = self.rolling.as_mut().expect("rolling writer");
+            rolling.record_written(bytes.len() as u64);
+            *offset += 1;
+        }
+
+        if let Some(rolling) = self.rolling.as_mut() {
+            rolling.prune();
+        }
+        Ok(())
+    }
+
+    /// Dump the unwritten buffer tail to the overflow path, emitting the
+    /// header only once across repeated dumps.
+    fn dump_overflow(
+        &mut self,
+        path: &std::path::Path,
+        states: &[(DateTime<Utc>, HashMap<String, Value>)],
+    ) -> Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(true)
+            .open(path)?;
+        let mut writer = Writer::from_writer(file);
+
+        // A pre-existing non-empty file already carries its header.
+        let has_content = std::fs::metadata(path).map(|m| m.len() > 0).unwrap_or(false);
+        let need_header = !self.overflow_header && !has_content;
+        if need_header {
+            writer.write_record(self.header_record())?;
+        }
+        for (time, state) in states {
+            writer.write_record(self.value_record(time, state))?;
         }
         writer.flush()?;
-        self.states = vec![];
+        self.overflow_header = true;
         Ok(())
     }
 }
+
+/// Serialize a single record to its CSV byte representation.
+fn encode_record(record: &[String]) -> Result<Vec<u8>> {
+    let mut writer = Writer::from_writer(vec![]);
+    writer.write_record(record)?;
+    writer.flush()?;
+    writer
+        .into_inner()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::SimulatedClock;
+    use std::sync::Arc;
+
+    fn tmp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("msr_recorder_{}_{}.csv", std::process::id(), name))
+    }
+
+    fn vals(v: i64) -> HashMap<String, Value> {
+        let mut m = HashMap::new();
+        m.insert("v".to_string(), Value::from(v));
+        m
+    }
+
+    fn int(state: &HashMap<String, Value>) -> i64 {
+        match state.get("v") {
+            Some(Value::Integer(i)) => *i,
+            other => panic!("unexpected value: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn round_trip_is_sorted_by_timestamp() {
+        let file = tmp_file("round_trip");
+        let _ = std::fs::remove_file(&file);
+
+        let clock = Arc::new(SimulatedClock::new(Utc.timestamp_millis(1_000)));
+        let cfg = CsvRecorderConfig {
+            file_name: file.clone(),
+            key_list: vec!["v".to_string()],
+            time_format: None,
+            rolling: None,
+            retry: None,
+            conversions: None,
+        };
+        let mut rec = CsvRecorder::with_clock(cfg, Box::new(clock.clone()));
+
+        rec.record_now(vals(1)); // t = 1000
+        clock.advance(Duration::from_secs(1));
+        rec.record_now(vals(3)); // t = 2000
+        rec.record(Utc.timestamp_millis(1_500), vals(2)); // out of order
+        rec.persist().unwrap();
+
+        let mut schema = HashMap::new();
+        schema.insert("v".to_string(), ValueKind::Integer);
+        let reader = CsvReader::new(CsvReaderConfig {
+            files: vec![file.clone()],
+            time_format: None,
+            schema,
+            since: None,
+            until: None,
+            template: None,
+        });
+        let rows: Vec<_> = reader.read().map(|r| r.unwrap()).collect();
+
+        let times: Vec<_> = rows.iter().map(|(t, _)| t.timestamp_millis()).collect();
+        assert_eq!(times, vec![1_000, 1_500, 2_000]);
+        let values: Vec<_> = rows.iter().map(|(_, s)| int(s)).collect();
+        assert_eq!(values, vec![1, 2, 3]);
+
+        let _ = std::fs::remove_file(&file);
+    }
+
+    #[test]
+    fn failed_persist_dumps_tail_to_overflow() {
+        let overflow = tmp_file("overflow");
+        let _ = std::fs::remove_file(&overflow);
+        // A path inside a non-existent directory makes every open attempt fail.
+        let bad = PathBuf::from("this_dir_does_not_exist_msr/out.csv");
+
+        let cfg = CsvRecorderConfig {
+            file_name: bad,
+            key_list: vec!["v".to_string()],
+            time_format: None,
+            rolling: None,
+            retry: Some(RetryConfig {
+                max_attempts: 2,
+                backoff: Duration::new(0, 0),
+                overflow: Some(overflow.clone()),
+            }),
+            conversions: None,
+        };
+        let mut rec = CsvRecorder::new(cfg);
+
+        // Two separate persist cycles both fail and dump to overflow.
+        rec.record(Utc.timestamp_millis(0), vals(1));
+        assert!(rec.persist().is_err());
+        rec.record(Utc.timestamp_millis(1_000), vals(2));
+        assert!(rec.persist().is_err());
+
+        assert_eq!(rec.status().dropped, 2);
+        assert_eq!(rec.status().pending, 0);
+
+        // The overflow file must carry exactly one header line.
+        let body = std::fs::read_to_string(&overflow).unwrap();
+        let headers = body
+            .lines()
+            .filter(|l| l.starts_with("timestamp_utc"))
+            .count();
+        assert_eq!(headers, 1);
+
+        let _ = std::fs::remove_file(&overflow);
+    }
+}
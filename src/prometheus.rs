@@ -0,0 +1,145 @@
+//! Prometheus scrape endpoint for live process values.
+
+use msr::Value;
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    net::{TcpListener, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+/// Maintains the latest recorded values and exposes them as metrics.
+#[derive(Clone, Default)]
+pub struct PrometheusExporter {
+    registry: Arc<Mutex<HashMap<String, Value>>>,
+}
+
+impl PrometheusExporter {
+    /// Create an empty exporter.
+    pub fn new() -> Self {
+        PrometheusExporter::default()
+    }
+
+    /// Merge the given values into the registry, overwriting existing keys.
+    ///
+    /// Keys missing from `values` keep their last known value.
+    pub fn update(&self, values: &HashMap<String, Value>) {
+        let mut registry = self.registry.lock().unwrap();
+        for (k, v) in values {
+            registry.insert(k.clone(), v.clone());
+        }
+    }
+
+    /// Render the current registry in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let registry = self.registry.lock().unwrap();
+        let mut out = String::new();
+        for (key, value) in registry.iter() {
+            if let Some(line) = metric_line(key, value) {
+                out.push_str(&line);
+                out.push('\n');
+            }
+        }
+        out
+    }
+
+    /// Serve the `/metrics` endpoint on the given address in a background
+    /// thread. Returns once the listener is bound.
+    pub fn serve<A: ToSocketAddrs>(&self, addr: A) -> std::io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        let exporter = self.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if let Ok(mut stream) = stream {
+                    let mut buf = [0u8; 1024];
+                    let _ = stream.read(&mut buf);
+                    let body = exporter.render();
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+                         Content-Length: {}\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    let _ = stream.write_all(response.as_bytes());
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Translate a single recorded `(key, value)` pair into a metric line.
+fn metric_line(key: &str, value: &Value) -> Option<String> {
+    // `rules` carries a comma-separated list of active rule ids.
+    if key == "rules" {
+        if let Value::Text(ref rules) = value {
+            let active: Vec<_> = rules
+                .split(',')
+                .filter(|r| !r.is_empty())
+                .map(|r| format!("msr_rule_active{{rule=\"{}\"}} 1", escape(r)))
+                .collect();
+            return if active.is_empty() {
+                None
+            } else {
+                Some(active.join("\n"))
+            };
+        }
+        return None;
+    }
+
+    // `fsm.<id>` carries the current state name as text.
+    if let Some(id) = key.strip_prefix("fsm.") {
+        if let Value::Text(ref state) = value {
+            return Some(format!(
+                "msr_fsm_state{{id=\"{}\",state=\"{}\"}} 1",
+                escape(id),
+                escape(state)
+            ));
+        }
+        return None;
+    }
+
+    // `controller.<id>.<type>.<field>` becomes a gauge with an `id` label.
+    if let Some(rest) = key.strip_prefix("controller.") {
+        let parts: Vec<_> = rest.splitn(3, '.').collect();
+        if let [id, ctype, field] = parts[..] {
+            let num = numeric(value)?;
+            return Some(format!(
+                "msr_controller_{}_{}{{id=\"{}\"}} {}",
+                sanitize(ctype),
+                sanitize(field),
+                escape(id),
+                num
+            ));
+        }
+    }
+
+    // Everything else becomes a plain gauge named after the key.
+    let num = numeric(value)?;
+    Some(format!("msr_{} {}", sanitize(key), num))
+}
+
+/// Coerce a numeric/boolean value into its gauge representation.
+fn numeric(value: &Value) -> Option<f64> {
+    match value {
+        Value::Decimal(d) => Some(*d),
+        Value::Integer(i) => Some(*i as f64),
+        Value::Bit(b) => Some(if *b { 1.0 } else { 0.0 }),
+        _ => None,
+    }
+}
+
+/// Sanitize a key fragment into a valid Prometheus metric name component.
+fn sanitize(s: &str) -> String {
+    s.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Escape a label value according to the text exposition format.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('"', "\\\"")
+}
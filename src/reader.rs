@@ -0,0 +1,223 @@
+//! Read recorded CSV segments back into decoded states.
+
+use crate::rolling::RollingFileNameTemplate;
+use chrono::prelude::*;
+use csv::{Reader, StringRecord};
+use msr::Value;
+use std::{collections::HashMap, fs::File, io::Result, path::PathBuf, time::Duration};
+
+/// The target [`Value`] variant a column should be decoded into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    Decimal,
+    Integer,
+    Bit,
+    Text,
+    /// Remaining milliseconds, decoded into `Value::Timeout`.
+    Timeout,
+    /// Elapsed-boolean (`true`/`false`) timeout, as written by the default
+    /// `TimeoutFormat`; decoded into a zero/non-zero `Value::Timeout`.
+    TimeoutElapsed,
+}
+
+/// Reader configuration.
+pub struct CsvReaderConfig {
+    /// Ordered list of segment files to read.
+    pub files: Vec<PathBuf>,
+    /// Same time formatting option as the recorder; `None` means the
+    /// timestamp column holds `timestamp_millis` integers.
+    pub time_format: Option<String>,
+    /// How to decode each column back into a `Value`.
+    pub schema: HashMap<String, ValueKind>,
+    /// Only yield rows at or after this instant.
+    pub since: Option<DateTime<Utc>>,
+    /// Only yield rows strictly before this instant.
+    pub until: Option<DateTime<Utc>>,
+    /// Template used to name segments; enables whole-file pruning by the
+    /// timestamp embedded in the file name.
+    pub template: Option<RollingFileNameTemplate>,
+}
+
+/// Reads decoded states from recorded CSV segments.
+pub struct CsvReader {
+    cfg: CsvReaderConfig,
+}
+
+impl CsvReader {
+    /// Create a new reader.
+    pub fn new(cfg: CsvReaderConfig) -> Self {
+        CsvReader { cfg }
+    }
+
+    /// Return a lazy iterator over the decoded rows of the (pruned) set of
+    /// segments, reading one record at a time so large historical ranges do
+    /// not have to be held in memory at once.
+    pub fn read(&self) -> CsvRows {
+        CsvRows {
+            files: self.relevant_files().into_iter(),
+            current: None,
+            time_format: self.cfg.time_format.clone(),
+            schema: self.cfg.schema.clone(),
+            since: self.cfg.since,
+            until: self.cfg.until,
+        }
+    }
+
+    /// Filter out segments whose embedded-timestamp span lies entirely
+    /// outside the requested range, cheaply, before opening any file.
+    fn relevant_files(&self) -> Vec<PathBuf> {
+        let template = match self.cfg.template {
+            Some(ref t) => t,
+            None => return self.cfg.files.clone(),
+        };
+
+        // Decode the start time of each file from its name; files without a
+        // decodable stamp are always kept.
+        let starts: Vec<Option<DateTime<Utc>>> = self
+            .cfg
+            .files
+            .iter()
+            .map(|f| template.decode_start(f))
+            .collect();
+
+        self.cfg
+            .files
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| {
+                let start = match starts[*i] {
+                    Some(s) => s,
+                    None => return true,
+                };
+                // The segment spans up to the start of the next segment.
+                let end = starts[i + 1..].iter().flatten().next().copied();
+                if let Some(until) = self.cfg.until {
+                    if start >= until {
+                        return false;
+                    }
+                }
+                if let (Some(since), Some(end)) = (self.cfg.since, end) {
+                    if end <= since {
+                        return false;
+                    }
+                }
+                true
+            })
+            .map(|(_, f)| f.clone())
+            .collect()
+    }
+}
+
+/// Lazy iterator over decoded `(timestamp, state)` rows.
+pub struct CsvRows {
+    files: std::vec::IntoIter<PathBuf>,
+    current: Option<(Reader<File>, StringRecord)>,
+    time_format: Option<String>,
+    schema: HashMap<String, ValueKind>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+}
+
+impl Iterator for CsvRows {
+    type Item = Result<(DateTime<Utc>, HashMap<String, Value>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.current.is_none() {
+                let file = self.files.next()?;
+                let mut reader = match Reader::from_path(&file) {
+                    Ok(r) => r,
+                    Err(e) => return Some(Err(e.into())),
+                };
+                let headers = match reader.headers() {
+                    Ok(h) => h.clone(),
+                    Err(e) => return Some(Err(e.into())),
+                };
+                self.current = Some((reader, headers));
+            }
+
+            let (reader, headers) = self.current.as_mut().unwrap();
+            let mut record = StringRecord::new();
+            match reader.read_record(&mut record) {
+                Ok(false) => {
+                    self.current = None;
+                    continue;
+                }
+                Err(e) => {
+                    self.current = None;
+                    return Some(Err(e.into()));
+                }
+                Ok(true) => {}
+            }
+
+            let time = match headers.iter().position(|h| h == "timestamp_utc") {
+                Some(idx) => match parse_time(&self.time_format, record.get(idx).unwrap_or("")) {
+                    Some(t) => t,
+                    None => continue,
+                },
+                None => continue,
+            };
+
+            if let Some(since) = self.since {
+                if time < since {
+                    continue;
+                }
+            }
+            if let Some(until) = self.until {
+                if time >= until {
+                    continue;
+                }
+            }
+
+            let mut map = HashMap::new();
+            for (idx, key) in headers.iter().enumerate() {
+                if key == "timestamp_utc" {
+                    continue;
+                }
+                let raw = record.get(idx).unwrap_or("");
+                if raw.is_empty() {
+                    continue;
+                }
+                if let Some(kind) = self.schema.get(key) {
+                    if let Some(value) = decode(raw, *kind) {
+                        map.insert(key.to_string(), value);
+                    }
+                }
+            }
+            return Some(Ok((time, map)));
+        }
+    }
+}
+
+fn parse_time(time_format: &Option<String>, raw: &str) -> Option<DateTime<Utc>> {
+    match time_format {
+        Some(fmt) => Utc.datetime_from_str(raw, fmt).ok(),
+        None => raw
+            .parse::<i64>()
+            .ok()
+            .and_then(|ms| Utc.timestamp_millis_opt(ms).single()),
+    }
+}
+
+/// Coerce a raw cell into the requested `Value` variant.
+fn decode(raw: &str, kind: ValueKind) -> Option<Value> {
+    match kind {
+        ValueKind::Decimal => raw.parse::<f64>().ok().map(Value::from),
+        ValueKind::Integer => raw.parse::<i64>().ok().map(Value::from),
+        ValueKind::Bit => raw.parse::<bool>().ok().map(Value::from),
+        ValueKind::Text => Some(Value::from(raw.to_string())),
+        ValueKind::Timeout => raw
+            .parse::<u64>()
+            .ok()
+            .map(|ms| Value::from(Duration::from_millis(ms))),
+        ValueKind::TimeoutElapsed => raw.parse::<bool>().ok().map(|elapsed| {
+            // The elapsed-boolean encoding is lossy; `false` maps to a
+            // non-zero placeholder so the timeout reads as "not elapsed".
+            Value::from(if elapsed {
+                Duration::new(0, 0)
+            } else {
+                Duration::from_millis(1)
+            })
+        }),
+    }
+}
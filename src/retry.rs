@@ -0,0 +1,32 @@
+//! Store-buffer / retry support for the CSV recorder.
+
+use std::{path::PathBuf, time::Duration};
+
+/// How [`persist`](crate::CsvRecorder::persist) reacts to write failures.
+pub struct RetryConfig {
+    /// Maximum number of flush attempts per `persist` call.
+    pub max_attempts: usize,
+    /// Delay inserted between attempts.
+    pub backoff: Duration,
+    /// Where to dump records that could not be written after all attempts.
+    pub overflow: Option<PathBuf>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_attempts: 3,
+            backoff: Duration::from_millis(100),
+            overflow: None,
+        }
+    }
+}
+
+/// Counters describing the recorder's backpressure state.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BufferStatus {
+    /// Records still buffered, awaiting a successful flush.
+    pub pending: usize,
+    /// Records diverted to the overflow path after exhausting all retries.
+    pub dropped: usize,
+}
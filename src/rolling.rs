@@ -0,0 +1,290 @@
+//! Rolling-file support for the CSV recorder.
+
+use chrono::prelude::*;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// How segment file names are derived from the configured base name.
+pub struct RollingFileNameTemplate {
+    /// Base path, e.g. `records/values.csv`.
+    pub base: PathBuf,
+    /// `strftime` pattern inserted between the file stem and its extension.
+    ///
+    /// With a base of `values.csv` and a pattern of `%Y%m%d-%H%M%S` a
+    /// segment started at midnight on new year becomes
+    /// `values-20200101-000000.csv`. When `None` a zero-padded sequence
+    /// number is used instead.
+    pub timestamp: Option<String>,
+}
+
+impl RollingFileNameTemplate {
+    /// Derive a segment path for the given start time and sequence number.
+    pub fn segment_path(&self, start: DateTime<Utc>, seq: u64) -> PathBuf {
+        let stem = self
+            .base
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let ext = self
+            .base
+            .extension()
+            .map(|e| e.to_string_lossy().into_owned());
+        let suffix = match self.timestamp {
+            Some(ref fmt) => start.format(fmt).to_string(),
+            None => format!("{:06}", seq),
+        };
+        let mut name = format!("{}-{}", stem, suffix);
+        if let Some(ext) = ext {
+            name.push('.');
+            name.push_str(&ext);
+        }
+        match self.base.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.join(name),
+            _ => PathBuf::from(name),
+        }
+    }
+
+    /// Decode the start time embedded in a segment file name.
+    ///
+    /// Returns `None` for sequence-numbered templates or when the suffix
+    /// does not match the configured timestamp pattern.
+    pub fn decode_start(&self, path: &Path) -> Option<DateTime<Utc>> {
+        let fmt = self.timestamp.as_ref()?;
+        let stem = self
+            .base
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let name = path.file_stem()?.to_string_lossy().into_owned();
+        let suffix = name.strip_prefix(&format!("{}-", stem))?;
+        Utc.datetime_from_str(suffix, fmt).ok()
+    }
+}
+
+/// Size and time limits that trigger a roll-over to a new segment.
+#[derive(Default)]
+pub struct RollingFileLimits {
+    /// Roll over before a write would take the active segment past this
+    /// many bytes. An empty segment always takes its first record, however
+    /// large.
+    pub max_size: Option<u64>,
+    /// Roll over once the active segment has been open for this long.
+    pub max_time_span: Option<Duration>,
+}
+
+/// Retention policy capping how many segments are kept on disk.
+#[derive(Default)]
+pub struct RetentionPolicy {
+    /// Keep at most this many segments, pruning the oldest first. The
+    /// newest segment is always kept, so `0` behaves like `1`.
+    pub max_segments: Option<usize>,
+    /// Keep at most this many bytes across all segments, pruning the oldest
+    /// first. The newest segment is always kept, even if it alone is larger.
+    pub max_total_bytes: Option<u64>,
+}
+
+/// Configuration for the rolling-file subsystem.
+pub struct RollingFileConfig {
+    /// How segment file names are generated.
+    pub template: RollingFileNameTemplate,
+    /// When to start a new segment.
+    pub limits: RollingFileLimits,
+    /// Which old segments to prune.
+    pub retention: RetentionPolicy,
+}
+
+/// Runtime state of the active segment, created lazily on the first write.
+pub struct RollingFileWriter {
+    cfg: RollingFileConfig,
+    seq: u64,
+    segments: Vec<PathBuf>,
+    active: Option<ActiveSegment>,
+}
+
+struct ActiveSegment {
+    path: PathBuf,
+    started_at: DateTime<Utc>,
+    bytes: u64,
+    needs_header: bool,
+}
+
+impl RollingFileWriter {
+    /// Create a writer for the given configuration.
+    pub fn new(cfg: RollingFileConfig) -> Self {
+        RollingFileWriter {
+            cfg,
+            seq: 0,
+            segments: vec![],
+            active: None,
+        }
+    }
+
+    /// Path of the active segment, opening a fresh one if necessary.
+    ///
+    /// Returns `true` alongside the path while the segment still needs its
+    /// CSV header. The flag stays set until [`record_written`] confirms a
+    /// successful write, so a failed first write to a freshly-rolled segment
+    /// does not permanently skip the header.
+    ///
+    /// [`record_written`]: RollingFileWriter::record_written
+    pub fn segment_for(&mut self, time: DateTime<Utc>, next_len: u64) -> (PathBuf, bool) {
+        if self.should_roll(time, next_len) {
+            self.active = None;
+        }
+        match self.active {
+            Some(ref seg) => (seg.path.clone(), seg.needs_header),
+            None => {
+                let path = self.cfg.template.segment_path(time, self.seq);
+                self.seq += 1;
+                self.segments.push(path.clone());
+                self.active = Some(ActiveSegment {
+                    path: path.clone(),
+                    started_at: time,
+                    bytes: 0,
+                    needs_header: true,
+                });
+                (path, true)
+            }
+        }
+    }
+
+    /// Account for `written` bytes successfully appended to the active
+    /// segment; the header is then known to be on disk.
+    pub fn record_written(&mut self, written: u64) {
+        if let Some(ref mut seg) = self.active {
+            seg.bytes += written;
+            seg.needs_header = false;
+        }
+    }
+
+    fn should_roll(&self, time: DateTime<Utc>, next_len: u64) -> bool {
+        let seg = match self.active {
+            Some(ref seg) => seg,
+            None => return false,
+        };
+        if let Some(max) = self.cfg.limits.max_size {
+            if seg.bytes > 0 && seg.bytes + next_len > max {
+                return true;
+            }
+        }
+        if let Some(span) = self.cfg.limits.max_time_span {
+            if let Ok(span) = chrono::Duration::from_std(span) {
+                if time - seg.started_at >= span {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Prune the oldest segments that exceed the retention policy.
+    pub fn prune(&mut self) {
+        if let Some(max) = self.cfg.retention.max_segments {
+            while self.segments.len() > max.max(1) {
+                let oldest = self.segments.remove(0);
+                remove_segment(&oldest);
+            }
+        }
+        if let Some(max) = self.cfg.retention.max_total_bytes {
+            while self.segments.len() > 1 && self.total_bytes() > max {
+                let oldest = self.segments.remove(0);
+                remove_segment(&oldest);
+            }
+        }
+    }
+
+    fn total_bytes(&self) -> u64 {
+        self.segments.iter().map(|p| segment_len(p)).sum()
+    }
+}
+
+fn segment_len(path: &Path) -> u64 {
+    fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+fn remove_segment(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        log::warn!("unable to prune segment '{}': {}", path.display(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template() -> RollingFileNameTemplate {
+        RollingFileNameTemplate {
+            base: PathBuf::from("rec.csv"),
+            timestamp: None,
+        }
+    }
+
+    #[test]
+    fn rolls_over_when_size_limit_exceeded() {
+        let cfg = RollingFileConfig {
+            template: template(),
+            limits: RollingFileLimits {
+                max_size: Some(10),
+                max_time_span: None,
+            },
+            retention: RetentionPolicy::default(),
+        };
+        let mut writer = RollingFileWriter::new(cfg);
+        let t = Utc.timestamp_millis(0);
+
+        let (first, needs_header) = writer.segment_for(t, 8);
+        assert!(needs_header);
+        writer.record_written(8);
+
+        // 8 + 8 > 10, so a new segment must start.
+        let (second, needs_header) = writer.segment_for(t, 8);
+        assert_ne!(first, second);
+        assert!(needs_header);
+    }
+
+    #[test]
+    fn header_stays_pending_until_write_is_confirmed() {
+        let cfg = RollingFileConfig {
+            template: template(),
+            limits: RollingFileLimits::default(),
+            retention: RetentionPolicy::default(),
+        };
+        let mut writer = RollingFileWriter::new(cfg);
+        let t = Utc.timestamp_millis(0);
+
+        let (first, needs_header) = writer.segment_for(t, 4);
+        assert!(needs_header);
+
+        // Simulate a failed first write: do not call `record_written`.
+        let (again, needs_header) = writer.segment_for(t, 4);
+        assert_eq!(first, again);
+        assert!(needs_header, "header must stay pending after a failed write");
+    }
+
+    #[test]
+    fn retention_caps_segment_count() {
+        let cfg = RollingFileConfig {
+            template: template(),
+            limits: RollingFileLimits {
+                max_size: Some(1),
+                max_time_span: None,
+            },
+            retention: RetentionPolicy {
+                max_segments: Some(2),
+                max_total_bytes: None,
+            },
+        };
+        let mut writer = RollingFileWriter::new(cfg);
+        let t = Utc.timestamp_millis(0);
+
+        for _ in 0..4 {
+            writer.segment_for(t, 8);
+            writer.record_written(8);
+        }
+        writer.prune();
+        assert!(writer.segments.len() <= 2);
+    }
+}